@@ -5,10 +5,30 @@ use crate::common::{
     slash_validator,
 };
 use crate::per_block_processing::errors::{BlockProcessingError, IntoWithIndex};
+use crate::per_block_processing::signature_sets::{
+    attester_slashing_signature_sets, bls_to_execution_change_signature_set, exit_signature_set,
+    indexed_attestation_signature_set, proposer_slashing_signature_sets,
+};
 use crate::VerifySignatures;
+use bls::SignatureSet;
 use safe_arith::SafeArith;
 use types::consts::altair::{PARTICIPATION_FLAG_WEIGHTS, PROPOSER_WEIGHT, WEIGHT_DENOMINATOR};
 
+/// Process all block operations, deferring BLS signature verification to a single batch check
+/// once every operation has produced its `SignatureSet`s.
+///
+/// State mutations (slashing a validator, initiating an exit, ...) still happen strictly in the
+/// order the operations appear in the block, since later operations in the same list can depend
+/// on earlier ones (e.g. a second exit for a validator already exiting). Only the *cryptographic*
+/// check of "is this signature valid" is deferred and batched for speed: every operation is first
+/// applied in order, and the signature sets are then collected in a separate pass against the
+/// resulting `state`.
+///
+/// If the aggregate batch check fails we fall back to re-verifying each collected `SignatureSet`
+/// individually, purely as a cryptographic check (never re-running the stateful `verify_*`
+/// checks, which would now spuriously fail against the already-mutated `state`). The error
+/// reports the offending operation's `index` scoped to its own operation list, the same way every
+/// other per-operation error in this module does.
 pub fn process_operations<T: EthSpec, Payload: AbstractExecPayload<T>>(
     state: &mut BeaconState<T>,
     block_body: BeaconBlockBodyRef<T, Payload>,
@@ -16,31 +36,209 @@ pub fn process_operations<T: EthSpec, Payload: AbstractExecPayload<T>>(
     ctxt: &mut ConsensusContext<T>,
     spec: &ChainSpec,
 ) -> Result<(), BlockProcessingError> {
+    let mut signature_sets = vec![];
+    let mut signature_set_origins = vec![];
+
     process_proposer_slashings(
         state,
         block_body.proposer_slashings(),
-        verify_signatures,
+        VerifySignatures::False,
         ctxt,
         spec,
     )?;
     process_attester_slashings(
         state,
         block_body.attester_slashings(),
-        verify_signatures,
+        VerifySignatures::False,
         ctxt,
         spec,
     )?;
-    process_attestations(state, block_body, verify_signatures, ctxt, spec)?;
+    process_attestations(state, block_body, VerifySignatures::False, ctxt, spec)?;
     process_deposits(state, block_body.deposits(), spec)?;
-    process_exits(state, block_body.voluntary_exits(), verify_signatures, spec)?;
+
+    // Post-EIP-6110 forks source additional deposits from the execution payload itself, already
+    // validated for inclusion by the EL, rather than from the `eth1_data` Merkle tree.
+    if let Ok(deposit_requests) = block_body.deposit_requests() {
+        process_deposit_requests(state, deposit_requests, spec)?;
+    }
+
+    process_exits(
+        state,
+        block_body.voluntary_exits(),
+        VerifySignatures::False,
+        spec,
+    )?;
 
     if let Ok(bls_to_execution_changes) = block_body.bls_to_execution_changes() {
-        process_bls_to_execution_changes(state, bls_to_execution_changes, verify_signatures, spec)?;
+        process_bls_to_execution_changes(
+            state,
+            bls_to_execution_changes,
+            VerifySignatures::False,
+            spec,
+        )?;
+    }
+
+    if verify_signatures.is_true() {
+        collect_operation_signature_sets(
+            state,
+            block_body,
+            ctxt,
+            spec,
+            &mut signature_sets,
+            &mut signature_set_origins,
+        )?;
+
+        if !batch_verify_signature_sets(&signature_sets) {
+            verify_signature_sets_individually(&signature_sets, &signature_set_origins)?;
+        }
     }
 
     Ok(())
 }
 
+/// Identifies which operation list a collected `SignatureSet` belongs to, and its position within
+/// that list, so a signature-verification failure can be reported with the same per-list `index`
+/// semantics as every other error in this module rather than a flat offset across every kind.
+#[derive(Clone, Copy)]
+enum SignatureSetOrigin {
+    ProposerSlashing(usize),
+    AttesterSlashing(usize),
+    Attestation(usize),
+    Exit(usize),
+    BlsToExecutionChange(usize),
+}
+
+impl SignatureSetOrigin {
+    fn invalid_signature_error(self) -> BlockProcessingError {
+        match self {
+            SignatureSetOrigin::ProposerSlashing(index) => {
+                BlockProcessingError::ProposerSlashingSignatureInvalid { index }
+            }
+            SignatureSetOrigin::AttesterSlashing(index) => {
+                BlockProcessingError::AttesterSlashingSignatureInvalid { index }
+            }
+            SignatureSetOrigin::Attestation(index) => {
+                BlockProcessingError::AttestationSignatureInvalid { index }
+            }
+            SignatureSetOrigin::Exit(index) => BlockProcessingError::ExitSignatureInvalid { index },
+            SignatureSetOrigin::BlsToExecutionChange(index) => {
+                BlockProcessingError::BlsToExecutionChangeSignatureInvalid { index }
+            }
+        }
+    }
+}
+
+/// Gathers the `SignatureSet` for every proposer slashing, attester slashing, attestation, exit
+/// and BLS-to-execution-change in the block, without performing any cryptographic check.
+///
+/// `origins` is extended in lockstep with `signature_sets`, recording which operation list (and
+/// position within it) each one came from.
+fn collect_operation_signature_sets<'a, T: EthSpec, Payload: AbstractExecPayload<T>>(
+    state: &'a BeaconState<T>,
+    block_body: BeaconBlockBodyRef<'a, T, Payload>,
+    ctxt: &mut ConsensusContext<T>,
+    spec: &'a ChainSpec,
+    signature_sets: &mut Vec<SignatureSet<'a>>,
+    origins: &mut Vec<SignatureSetOrigin>,
+) -> Result<(), BlockProcessingError> {
+    for (i, proposer_slashing) in block_body.proposer_slashings().iter().enumerate() {
+        let sets = proposer_slashing_signature_sets(state, proposer_slashing, spec)?;
+        origins.extend(sets.iter().map(|_| SignatureSetOrigin::ProposerSlashing(i)));
+        signature_sets.extend(sets);
+    }
+
+    for (i, attester_slashing) in block_body.attester_slashings().iter().enumerate() {
+        let sets = attester_slashing_signature_sets(state, attester_slashing, spec)?;
+        origins.extend(sets.iter().map(|_| SignatureSetOrigin::AttesterSlashing(i)));
+        signature_sets.extend(sets);
+    }
+
+    for (i, attestation) in block_body.attestations().iter().enumerate() {
+        let indexed_attestation = ctxt.get_indexed_attestation(state, attestation)?;
+        signature_sets.push(indexed_attestation_signature_set(
+            state,
+            indexed_attestation,
+            &attestation.signature,
+            spec,
+        )?);
+        origins.push(SignatureSetOrigin::Attestation(i));
+    }
+
+    for (i, exit) in block_body.voluntary_exits().iter().enumerate() {
+        signature_sets.push(exit_signature_set(state, exit, spec)?);
+        origins.push(SignatureSetOrigin::Exit(i));
+    }
+
+    if let Ok(bls_to_execution_changes) = block_body.bls_to_execution_changes() {
+        for (i, signed_address_change) in bls_to_execution_changes.iter().enumerate() {
+            signature_sets.push(bls_to_execution_change_signature_set(
+                state,
+                signed_address_change,
+                spec,
+            )?);
+            origins.push(SignatureSetOrigin::BlsToExecutionChange(i));
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify every collected `SignatureSet` in one aggregate, random-coefficient batch check.
+///
+/// Batch verification scales each `(signature, message, pubkeys)` triple by an independently
+/// sampled random 64-bit coefficient before aggregating, so a forged signature cannot be crafted
+/// to cancel out against another triple in the batch. Returns `true` iff every signature in the
+/// batch is valid; callers are responsible for falling back to a per-operation check to identify
+/// which one failed.
+fn batch_verify_signature_sets(signature_sets: &[SignatureSet]) -> bool {
+    if signature_sets.is_empty() {
+        return true;
+    }
+
+    bls::verify_signature_sets(signature_sets.iter())
+}
+
+/// Re-verifies each collected `SignatureSet` individually, purely as a cryptographic check
+/// against the sets already gathered by [`collect_operation_signature_sets`] — never against the
+/// operations themselves, since by this point `state` has already had every non-signature check
+/// applied (slashings marked, exits initiated, withdrawal credentials changed), which would make
+/// re-running the stateful `verify_*` checks fail for the wrong reason.
+///
+/// Only reached once [`batch_verify_signature_sets`] has already reported the aggregate batch as
+/// invalid, so this is expected to return an error reporting the first invalid signature's
+/// `index` within its own operation list, via `origins`.
+fn verify_signature_sets_individually(
+    signature_sets: &[SignatureSet],
+    origins: &[SignatureSetOrigin],
+) -> Result<(), BlockProcessingError> {
+    if let Some(error) =
+        first_invalid_signature_error(signature_sets.iter().map(|s| s.verify()), origins)
+    {
+        return Err(error);
+    }
+
+    // All signatures verified individually but the aggregate batch still failed; this should be
+    // unreachable outside of an implementation bug in the batch combination itself.
+    Err(BlockProcessingError::SignatureSetError(
+        bls::Error::InvalidSignature,
+    ))
+}
+
+/// Given the per-set verification outcome (`true` == valid) for each collected `SignatureSet`,
+/// returns the error for the first invalid one, or `None` if every signature checked out.
+///
+/// Split out from [`verify_signature_sets_individually`] so the index-translation logic can be
+/// unit-tested without needing real `SignatureSet`s.
+fn first_invalid_signature_error(
+    results: impl Iterator<Item = bool>,
+    origins: &[SignatureSetOrigin],
+) -> Option<BlockProcessingError> {
+    results
+        .zip(origins.iter())
+        .find(|(is_valid, _)| !is_valid)
+        .map(|(_, origin)| origin.invalid_signature_error())
+}
+
 pub mod base {
     use super::*;
 
@@ -336,10 +534,16 @@ pub fn process_deposits<T: EthSpec>(
     deposits: &[Deposit],
     spec: &ChainSpec,
 ) -> Result<(), BlockProcessingError> {
-    let expected_deposit_len = std::cmp::min(
-        T::MaxDeposits::to_u64(),
-        state.get_outstanding_deposit_len()?,
+    let expected_deposit_len = capped_outstanding_deposit_len(
+        std::cmp::min(
+            T::MaxDeposits::to_u64(),
+            state.get_outstanding_deposit_len()?,
+        ),
+        state.eth1_deposit_index(),
+        state.deposit_requests_start_index()?,
+        spec.unset_deposit_requests_start_index,
     );
+
     block_verify!(
         deposits.len() as u64 == expected_deposit_len,
         BlockProcessingError::DepositCountInvalid {
@@ -370,6 +574,73 @@ pub fn process_deposits<T: EthSpec>(
     Ok(())
 }
 
+/// Caps the number of `eth1_data`-sourced deposits expected in this block at the EIP-6110
+/// boundary: once `deposit_requests_start_index` is set, no deposit at or past that index may
+/// still be sourced from the `eth1_data` Merkle tree, since the execution layer has already
+/// handed it to us as a `DepositRequest` instead.
+fn capped_outstanding_deposit_len(
+    outstanding_deposit_len: u64,
+    eth1_deposit_index: u64,
+    deposit_requests_start_index: u64,
+    unset_deposit_requests_start_index: u64,
+) -> u64 {
+    if deposit_requests_start_index == unset_deposit_requests_start_index {
+        return outstanding_deposit_len;
+    }
+
+    let outstanding_before_boundary =
+        deposit_requests_start_index.saturating_sub(eth1_deposit_index);
+    std::cmp::min(outstanding_deposit_len, outstanding_before_boundary)
+}
+
+/// Processes each `DepositRequest`, updating the state unconditionally (there's nothing to
+/// reject: see [`process_deposit_request`]).
+///
+/// Unlike [`process_deposits`], the inclusion of these deposits has already been validated by the
+/// execution layer, so there is no Merkle proof to check and no `eth1_deposit_index` to advance.
+/// Each request is instead queued onto the pending deposit queue rather than immediately creating
+/// or crediting a validator; the queue is drained during epoch processing.
+pub fn process_deposit_requests<T: EthSpec>(
+    state: &mut BeaconState<T>,
+    deposit_requests: &[DepositRequest],
+    spec: &ChainSpec,
+) -> Result<(), BlockProcessingError> {
+    for (i, deposit_request) in deposit_requests.iter().enumerate() {
+        process_deposit_request(state, deposit_request, spec).map_err(|e| e.into_with_index(i))?;
+    }
+
+    Ok(())
+}
+
+/// Process a single `DepositRequest` sourced from the execution payload.
+///
+/// Unlike the `eth1_data` path, ordering of `DepositRequest`s is not re-validated here: the queue
+/// they're appended to (`pending_deposits`) is drained during epoch processing, so there is no
+/// stable count of "requests since the boundary was set" to check a strictly-incrementing index
+/// against. Sequencing is the execution layer's responsibility, just as inclusion is: no Merkle
+/// proof is checked for a `DepositRequest`.
+pub fn process_deposit_request<T: EthSpec>(
+    state: &mut BeaconState<T>,
+    deposit_request: &DepositRequest,
+    spec: &ChainSpec,
+) -> Result<(), BlockProcessingError> {
+    // Set the `deposit_requests_start_index` the first time a deposit request is processed; from
+    // then on all deposit requests are known to be past the `eth1_data` deposit boundary.
+    if state.deposit_requests_start_index()? == spec.unset_deposit_requests_start_index {
+        *state.deposit_requests_start_index_mut()? = deposit_request.index;
+    }
+
+    state.pending_deposits_mut()?.push(PendingDeposit {
+        pubkey: deposit_request.pubkey,
+        withdrawal_credentials: deposit_request.withdrawal_credentials,
+        amount: deposit_request.amount,
+        signature: deposit_request.signature.clone(),
+        slot: state.slot(),
+    })?;
+
+    Ok(())
+}
+
 /// Process a single deposit, optionally verifying its merkle proof.
 pub fn process_deposit<T: EthSpec>(
     state: &mut BeaconState<T>,
@@ -433,3 +704,84 @@ pub fn process_deposit<T: EthSpec>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNSET: u64 = u64::MAX;
+
+    #[test]
+    fn capped_outstanding_deposit_len_unaffected_before_boundary_is_set() {
+        assert_eq!(
+            capped_outstanding_deposit_len(16, 100, UNSET, UNSET),
+            16,
+            "eth1 deposits are uncapped while deposit_requests_start_index is unset"
+        );
+    }
+
+    #[test]
+    fn capped_outstanding_deposit_len_caps_at_boundary() {
+        // Only 5 eth1 deposits remain before the EIP-6110 boundary, even though 16 were
+        // otherwise outstanding.
+        assert_eq!(capped_outstanding_deposit_len(16, 100, 105, UNSET), 5);
+    }
+
+    #[test]
+    fn capped_outstanding_deposit_len_zero_once_boundary_passed() {
+        assert_eq!(capped_outstanding_deposit_len(16, 105, 100, UNSET), 0);
+    }
+
+    // Mirrors a mixed-operation block: one proposer slashing (two signature sets), two exits and
+    // one BLS-to-execution-change. Each test supplies its own per-set validity results.
+    fn mixed_block_origins() -> Vec<SignatureSetOrigin> {
+        vec![
+            SignatureSetOrigin::ProposerSlashing(0),
+            SignatureSetOrigin::ProposerSlashing(0),
+            SignatureSetOrigin::Exit(0),
+            SignatureSetOrigin::Exit(1),
+            SignatureSetOrigin::BlsToExecutionChange(0),
+        ]
+    }
+
+    #[test]
+    fn first_invalid_signature_error_none_when_batch_succeeds() {
+        let origins = mixed_block_origins();
+        let all_valid = std::iter::repeat(true).take(origins.len());
+
+        assert!(first_invalid_signature_error(all_valid, &origins).is_none());
+    }
+
+    #[test]
+    fn first_invalid_signature_error_reports_the_right_operation_list_and_index() {
+        let origins = mixed_block_origins();
+        // Every set valid except the second exit's (index 3 in the flat, concatenated list).
+        let results = vec![true, true, true, false, true].into_iter();
+
+        match first_invalid_signature_error(results, &origins) {
+            Some(BlockProcessingError::ExitSignatureInvalid { index }) => assert_eq!(index, 1),
+            other => panic!(
+                "expected ExitSignatureInvalid {{ index: 1 }}, got {:?}",
+                other
+            ),
+        }
+    }
+
+    #[test]
+    fn first_invalid_signature_error_does_not_confuse_operation_lists() {
+        // The first proposer slashing's second signature set is bad; this must not be reported
+        // against the exits or BLS-to-execution-changes that follow it in the flat list.
+        let origins = mixed_block_origins();
+        let results = vec![true, false, true, true, true].into_iter();
+
+        match first_invalid_signature_error(results, &origins) {
+            Some(BlockProcessingError::ProposerSlashingSignatureInvalid { index }) => {
+                assert_eq!(index, 0)
+            }
+            other => panic!(
+                "expected ProposerSlashingSignatureInvalid {{ index: 0 }}, got {:?}",
+                other
+            ),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Construction of the cryptographic `SignatureSet` for each block operation, kept separate
+//! from the actual verification so that callers (namely `process_operations`) can gather the
+//! sets for every operation in a block and verify them all at once.
+use super::*;
+use bls::SignatureSet;
+use types::{AttesterSlashing, ProposerSlashing, SignedBlsToExecutionChange, SignedVoluntaryExit};
+
+/// Returns the signature set for the two signed headers contained in a `ProposerSlashing`.
+pub fn proposer_slashing_signature_sets<'a, T: EthSpec>(
+    state: &'a BeaconState<T>,
+    proposer_slashing: &'a ProposerSlashing,
+    spec: &'a ChainSpec,
+) -> Result<[SignatureSet<'a>; 2], BlockProcessingError> {
+    let proposer_index = proposer_slashing.signed_header_1.message.proposer_index as usize;
+    let pubkey = state
+        .get_validator(proposer_index)
+        .map_err(BlockProcessingError::BeaconStateError)?
+        .pubkey
+        .decompress()?;
+
+    Ok([
+        block_header_signature_set(
+            state,
+            &proposer_slashing.signed_header_1,
+            Cow::Owned(pubkey.clone()),
+            spec,
+        )?,
+        block_header_signature_set(
+            state,
+            &proposer_slashing.signed_header_2,
+            Cow::Owned(pubkey),
+            spec,
+        )?,
+    ])
+}
+
+/// Returns the signature sets for the two indexed attestations contained in an `AttesterSlashing`.
+pub fn attester_slashing_signature_sets<'a, T: EthSpec>(
+    state: &'a BeaconState<T>,
+    attester_slashing: &'a AttesterSlashing<T>,
+    spec: &'a ChainSpec,
+) -> Result<[SignatureSet<'a>; 2], BlockProcessingError> {
+    Ok([
+        indexed_attestation_signature_set(
+            state,
+            &attester_slashing.attestation_1,
+            &attester_slashing.attestation_1.signature,
+            spec,
+        )?,
+        indexed_attestation_signature_set(
+            state,
+            &attester_slashing.attestation_2,
+            &attester_slashing.attestation_2.signature,
+            spec,
+        )?,
+    ])
+}
+
+/// Returns the signature set for a `SignedVoluntaryExit`.
+pub fn exit_signature_set<'a, T: EthSpec>(
+    state: &'a BeaconState<T>,
+    signed_exit: &'a SignedVoluntaryExit,
+    spec: &'a ChainSpec,
+) -> Result<SignatureSet<'a>, BlockProcessingError> {
+    let validator_index = signed_exit.message.validator_index as usize;
+    let pubkey = state
+        .get_validator(validator_index)
+        .map_err(BlockProcessingError::BeaconStateError)?
+        .pubkey
+        .decompress()?;
+    let domain = spec.get_domain(
+        signed_exit.message.epoch,
+        Domain::VoluntaryExit,
+        &state.fork(),
+        state.genesis_validators_root(),
+    );
+    let message = signed_exit.message.signing_root(domain);
+
+    Ok(SignatureSet::single_pubkey(
+        &signed_exit.signature,
+        Cow::Owned(pubkey),
+        message,
+    ))
+}
+
+/// Returns the signature set for a `SignedBlsToExecutionChange`.
+pub fn bls_to_execution_change_signature_set<'a, T: EthSpec>(
+    state: &'a BeaconState<T>,
+    signed_address_change: &'a SignedBlsToExecutionChange,
+    spec: &'a ChainSpec,
+) -> Result<SignatureSet<'a>, BlockProcessingError> {
+    let domain = spec.compute_domain(
+        Domain::BlsToExecutionChange,
+        spec.genesis_fork_version,
+        state.genesis_validators_root(),
+    );
+    let message = signed_address_change.message.signing_root(domain);
+    let pubkey = signed_address_change.message.from_bls_pubkey.decompress()?;
+
+    Ok(SignatureSet::single_pubkey(
+        &signed_address_change.signature,
+        Cow::Owned(pubkey),
+        message,
+    ))
+}
+
+fn block_header_signature_set<'a, T: EthSpec>(
+    state: &'a BeaconState<T>,
+    signed_header: &'a SignedBeaconBlockHeader,
+    pubkey: Cow<'a, PublicKey>,
+    spec: &'a ChainSpec,
+) -> Result<SignatureSet<'a>, BlockProcessingError> {
+    let domain = spec.get_domain(
+        signed_header.message.slot.epoch(T::slots_per_epoch()),
+        Domain::BeaconProposer,
+        &state.fork(),
+        state.genesis_validators_root(),
+    );
+    let message = signed_header.message.signing_root(domain);
+
+    Ok(SignatureSet::single_pubkey(
+        &signed_header.signature,
+        pubkey,
+        message,
+    ))
+}
+
+pub fn indexed_attestation_signature_set<'a, T: EthSpec>(
+    state: &'a BeaconState<T>,
+    indexed_attestation: &'a IndexedAttestation<T>,
+    signature: &'a AggregateSignature,
+    spec: &'a ChainSpec,
+) -> Result<SignatureSet<'a>, BlockProcessingError> {
+    let pubkeys = indexed_attestation
+        .attesting_indices
+        .iter()
+        .map(|&validator_idx| {
+            let pubkey = state
+                .get_validator(validator_idx as usize)
+                .map_err(BlockProcessingError::BeaconStateError)?
+                .pubkey
+                .decompress()?;
+            Ok(Cow::Owned(pubkey))
+        })
+        .collect::<Result<Vec<_>, BlockProcessingError>>()?;
+
+    let domain = spec.get_domain(
+        indexed_attestation.data.target.epoch,
+        Domain::BeaconAttester,
+        &state.fork(),
+        state.genesis_validators_root(),
+    );
+    let message = indexed_attestation.data.signing_root(domain);
+
+    Ok(SignatureSet::multiple_pubkeys(signature, pubkeys, message))
+}
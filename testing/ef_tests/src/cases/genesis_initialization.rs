@@ -2,7 +2,7 @@ use super::*;
 use crate::case_result::compare_beacon_state_results_without_caches;
 use crate::decode::{ssz_decode_file, ssz_decode_file_with, ssz_decode_state, yaml_decode_file};
 use serde::Deserialize;
-use state_processing::initialize_beacon_state_from_eth1;
+use state_processing::{initialize_beacon_state_from_eth1, is_valid_genesis_state};
 use std::path::PathBuf;
 use types::{BeaconState, Deposit, EthSpec, ExecutionPayloadHeader, ForkName, Hash256};
 
@@ -10,6 +10,7 @@ use types::{BeaconState, Deposit, EthSpec, ExecutionPayloadHeader, ForkName, Has
 struct Metadata {
     deposits_count: usize,
     execution_payload_header: Option<bool>,
+    is_valid_genesis: Option<bool>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -26,6 +27,7 @@ pub struct GenesisInitialization<E: EthSpec> {
     pub eth1_timestamp: u64,
     pub deposits: Vec<Deposit>,
     pub execution_payload_header: Option<ExecutionPayloadHeader<E>>,
+    pub is_valid_genesis: Option<bool>,
     pub state: Option<BeaconState<E>>,
 }
 
@@ -60,6 +62,7 @@ impl<E: EthSpec> LoadCase for GenesisInitialization<E> {
             eth1_timestamp,
             deposits,
             execution_payload_header,
+            is_valid_genesis: meta.is_valid_genesis,
             state: Some(state),
         })
     }
@@ -82,6 +85,36 @@ impl<E: EthSpec> Case for GenesisInitialization<E> {
             spec,
         );
 
+        // Beyond just checking the resulting state, exercise the genesis validity predicate and,
+        // for Bellatrix+ genesis, confirm the execution payload header round-trips untouched.
+        if let Some(expected_is_valid_genesis) = self.is_valid_genesis {
+            let is_valid_genesis = result
+                .as_ref()
+                .map(|state| is_valid_genesis_state(state, spec))
+                .unwrap_or(false);
+
+            if is_valid_genesis != expected_is_valid_genesis {
+                return Err(Error::NotEqual(format!(
+                    "is_valid_genesis: expected {}, got {}",
+                    expected_is_valid_genesis, is_valid_genesis
+                )));
+            }
+        }
+
+        if let Some(expected_header) = &self.execution_payload_header {
+            if let Ok(state) = &result {
+                let produced_header = state
+                    .latest_execution_payload_header()
+                    .map_err(|e| Error::FailedToParseTest(format!("{:?}", e)))?;
+
+                if &ExecutionPayloadHeader::from(produced_header) != expected_header {
+                    return Err(Error::NotEqual(
+                        "latest_execution_payload_header did not match the loaded header".into(),
+                    ));
+                }
+            }
+        }
+
         let mut expected = self.state.clone();
 
         compare_beacon_state_results_without_caches(&mut result, &mut expected)